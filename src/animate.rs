@@ -0,0 +1,164 @@
+//! Software-driven lighting effects.
+//!
+//! The NCT6795D only ever cycles through 8 precomputed 4-bit frames per
+//! channel at a fixed step duration, so anything smoother (a rainbow, a
+//! reactive temperature gradient, ...) has to be driven from software: keep
+//! the device open, recompute the RR/GG/BB frame words on a tick, and write
+//! them back. `fff` is left enabled and all 8 frames are kept identical each
+//! tick, so the chip's own step-cycling degenerates into a solid colour that
+//! we simply rewrite before anyone notices it hasn't moved.
+
+use std::fs::{self, File};
+use std::io::Read;
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use {REDCELL, GREENCELL, BLUECELL, write_colour, write_byte_to_cell, enable_rgb_bank,
+     check_chip_identity};
+use color::fill_frames;
+use ResultExt;
+
+static SHOULD_STOP: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn request_stop(_signum: i32) {
+    SHOULD_STOP.store(true, Ordering::SeqCst);
+}
+
+extern "C" {
+    fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+    fn flock(fd: i32, operation: i32) -> i32;
+}
+
+const SIGINT: i32 = 2;
+const SIGTERM: i32 = 15;
+const LOCK_EX: i32 = 2;
+const LOCK_NB: i32 = 4;
+
+const DEFAULT_LOCK_PATH: &str = "/var/lock/msi-rgb.lock";
+
+fn install_signal_handlers() {
+    unsafe {
+        signal(SIGINT, request_stop);
+        signal(SIGTERM, request_stop);
+    }
+}
+
+/// Held for as long as the daemon runs; dropping it (or exiting) releases
+/// the advisory lock on the backing file.
+#[allow(dead_code)]
+struct DaemonLock(File);
+
+fn acquire_lock() -> ::Result<DaemonLock> {
+    let f = fs::OpenOptions::new().create(true).write(true).truncate(false).open(DEFAULT_LOCK_PATH)
+        .chain_err(|| format!("could not open lock file {}", DEFAULT_LOCK_PATH))?;
+    if unsafe { flock(f.as_raw_fd(), LOCK_EX | LOCK_NB) } != 0 {
+        return Err(format!("another msi-rgb daemon appears to already be running \
+                            (could not lock {})", DEFAULT_LOCK_PATH).into());
+    }
+    Ok(DaemonLock(f))
+}
+
+/// A built-in software animation.
+pub enum Mode {
+    /// Cycles the hue of a fully-saturated colour through the whole wheel.
+    Rainbow,
+    /// Maps a temperature read from a sysfs file linearly from blue (cold)
+    /// to red (hot).
+    Thermal { sensor_path: String },
+}
+
+impl Mode {
+    pub fn from_arg(mode: &str, sensor_path: Option<&str>) -> ::Result<Mode> {
+        match mode {
+            "rainbow" => Ok(Mode::Rainbow),
+            "thermal" => {
+                let path = sensor_path.ok_or("`--animate thermal` requires `--thermal-sensor \
+                                              PATH`")?;
+                Ok(Mode::Thermal { sensor_path: path.to_string() })
+            }
+            other => Err(format!("unknown animation mode: {}", other).into()),
+        }
+    }
+}
+
+/// Converts a hue in `[0, 360)` (full saturation, full value) to 4-bit
+/// red/green/blue levels: the hue determines a sector of the wheel
+/// (`sector = floor(hue / 60)`), and within that sector one channel is at
+/// max, one is at min, and the third ramps linearly between them.
+fn hue_to_rgb4(hue: f64) -> (u8, u8, u8) {
+    let mut hue = hue % 360.0;
+    if hue < 0.0 {
+        hue += 360.0;
+    }
+    let sector = (hue / 60.0).floor() as i32;
+    let frac = hue / 60.0 - sector as f64;
+    let max = 0xfu8;
+    let min = 0x0u8;
+    let rising = (frac * max as f64).round() as u8;
+    let falling = max - rising;
+    match sector {
+        0 => (max, rising, min),
+        1 => (falling, max, min),
+        2 => (min, max, rising),
+        3 => (min, falling, max),
+        4 => (rising, min, max),
+        _ => (max, min, falling),
+    }
+}
+
+fn read_millidegrees(path: &str) -> ::Result<i64> {
+    let mut s = String::new();
+    fs::File::open(path).chain_err(|| format!("could not open {}", path))?
+        .read_to_string(&mut s).chain_err(|| format!("could not read {}", path))?;
+    s.trim().parse::<i64>().chain_err(|| format!("{} did not contain an integer temperature", path))
+}
+
+/// Linearly maps a temperature between `lo` and `hi` millidegrees to a
+/// blue-to-red 4-bit gradient, clamped at the ends.
+fn thermal_to_rgb4(millidegrees: i64, lo: i64, hi: i64) -> (u8, u8, u8) {
+    let t = ((millidegrees - lo) as f64 / (hi - lo) as f64).clamp(0.0, 1.0);
+    let level = (t * 0xf as f64).round() as u8;
+    (level, 0, 0xf - level)
+}
+
+/// Keeps the device open and rewrites the RGB frame cells on every tick until
+/// a SIGINT/SIGTERM is received, at which point the RGB subsystem is turned
+/// back off and the function returns. The caller is expected to have already
+/// entered/will leave advanced mode around this call, same as `run()`.
+pub fn run_daemon(f: &mut File, base_port: u16, mode: Mode, tick: Duration,
+                  ignore: bool) -> ::Result<()> {
+    let _lock = acquire_lock()?;
+    install_signal_handlers();
+
+    check_chip_identity(f, base_port, ignore, false)?;
+    enable_rgb_bank(f, base_port)?;
+
+    // fff = 1 (no fade-in), no invert, lights on, fastest step duration —
+    // every tick rewrites all 8 frames to the same colour, so the chip's own
+    // cycling never has anything to show.
+    write_byte_to_cell(f, base_port, 0xe4, 0)?;
+    write_byte_to_cell(f, base_port, 0xfe, 0)?;
+    write_byte_to_cell(f, base_port, 0xff, 0b11100010)?;
+
+    let mut t = 0.0f64;
+    while !SHOULD_STOP.load(Ordering::SeqCst) {
+        let (r, g, b) = match mode {
+            Mode::Rainbow => hue_to_rgb4(t),
+            Mode::Thermal { ref sensor_path } => {
+                let temp = read_millidegrees(sensor_path)?;
+                thermal_to_rgb4(temp, 40_000, 90_000)
+            }
+        };
+        write_colour(f, base_port, REDCELL, fill_frames(r))?;
+        write_colour(f, base_port, GREENCELL, fill_frames(g))?;
+        write_colour(f, base_port, BLUECELL, fill_frames(b))?;
+
+        thread::sleep(tick);
+        t = (t + 2.0) % 360.0;
+    }
+
+    write_byte_to_cell(f, base_port, 0xe4, 1)?;
+    Ok(())
+}
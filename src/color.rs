@@ -0,0 +1,82 @@
+//! 24-bit `#RRGGBB` colours, mapped onto the chip's 4-bit-per-channel,
+//! 8-frame layout.
+//!
+//! Each channel only has 16 distinct levels (the `EE` enable bits), so
+//! mapping an 8-bit component down to 4 bits with a raw `>> 4` truncation
+//! crushes anything dim to black. Instead each component goes through a
+//! gamma (~2.2) curve before being packed into the frames —
+//! either all 8 identically, or interpolated across them when a second
+//! colour is given.
+
+/// Display gamma the 4-bit levels are assumed to approximate.
+const GAMMA: f64 = 2.2;
+
+/// Maps an 8-bit channel value to a 4-bit frame level through a gamma curve
+/// (computed on each call) rather than simple bit truncation, so low
+/// intensities don't crush to black.
+fn to_nibble(component: u8) -> u8 {
+    let normalized = component as f64 / 255.0;
+    let corrected = normalized.powf(1.0 / GAMMA);
+    (corrected * 15.0).round() as u8
+}
+
+/// A parsed `#RRGGBB` colour.
+pub struct Colour {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Colour {
+    pub fn parse(s: &str) -> ::Result<Colour> {
+        let s = s.trim_start_matches('#');
+        if s.len() != 6 || !s.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("`{}` is not a 24 bit `#RRGGBB` colour", s).into());
+        }
+        Ok(Colour {
+            r: u8::from_str_radix(&s[0..2], 16)?,
+            g: u8::from_str_radix(&s[2..4], 16)?,
+            b: u8::from_str_radix(&s[4..6], 16)?,
+        })
+    }
+}
+
+/// Packs one 4-bit level into all 8 frames of a channel word, the layout
+/// `write_colour` expects (frames interleaved as `10 32 54 76` per byte).
+pub fn fill_frames(level: u8) -> u32 {
+    let nibble = (level & 0xf) as u32;
+    let byte = nibble | (nibble << 4);
+    byte << 24 | byte << 16 | byte << 8 | byte
+}
+
+/// Packs 8 (possibly distinct) 4-bit levels, one per frame `0..=7`, into the
+/// `data: u32` `write_colour` expects.
+fn pack_frames(levels: [u8; 8]) -> u32 {
+    let byte = |lo: u8, hi: u8| (lo & 0xf) as u32 | ((hi & 0xf) as u32) << 4;
+    byte(levels[0], levels[1]) << 24 |
+    byte(levels[2], levels[3]) << 16 |
+    byte(levels[4], levels[5]) << 8 |
+    byte(levels[6], levels[7])
+}
+
+/// Computes the `(red, green, blue)` frame words `write_colour` expects for
+/// a single colour, or a gradient across the 8 hardware frames between `c1`
+/// and `c2` when a second stop is given.
+pub fn words(c1: &Colour, c2: Option<&Colour>) -> (u32, u32, u32) {
+    let channel = |get: fn(&Colour) -> u8| {
+        let start = to_nibble(get(c1));
+        match c2 {
+            None => fill_frames(start),
+            Some(c2) => {
+                let end = to_nibble(get(c2));
+                let mut levels = [0u8; 8];
+                for (i, level) in levels.iter_mut().enumerate() {
+                    let t = i as f64 / 7.0;
+                    *level = (start as f64 + (end as f64 - start as f64) * t).round() as u8;
+                }
+                pack_frames(levels)
+            }
+        }
+    };
+    (channel(|c| c.r), channel(|c| c.g), channel(|c| c.b))
+}
@@ -0,0 +1,209 @@
+//! Named colour profiles, persisted to a small INI-like config file.
+//!
+//! This lets `--profile NAME` stand in for the RED/GREEN/BLUE and friends
+//! arguments that `run()` would otherwise expect on the command line, and lets
+//! `--save-profile NAME` write the currently-resolved arguments back out under
+//! that name.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+
+use clap::ArgMatches;
+use ResultExt;
+
+/// Keys recognised inside a profile section, in the order they're written out.
+const KEYS: [&str; 8] =
+    ["red", "green", "blue", "pulse", "duration", "blink", "invert", "fade-in"];
+
+/// A single named colour profile, as stored in the config file.
+#[derive(Default, Clone)]
+pub struct Profile {
+    pub entries: BTreeMap<String, String>,
+}
+
+impl Profile {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries.get(key).map(String::as_str)
+    }
+}
+
+/// The parsed config file: a set of named profiles, plus the path it was (or
+/// will be) read from and written to.
+pub struct ProfileStore {
+    path: PathBuf,
+    profiles: BTreeMap<String, Profile>,
+}
+
+impl ProfileStore {
+    /// Load the store from `path`. A missing file is treated as an empty
+    /// store rather than an error, since `--save-profile` may be creating it
+    /// for the first time.
+    pub fn load(path: PathBuf) -> ::Result<ProfileStore> {
+        let mut profiles = BTreeMap::new();
+        match fs::read_to_string(&path) {
+            Ok(contents) => {
+                let mut current: Option<String> = None;
+                for (lineno, raw_line) in contents.lines().enumerate() {
+                    let line = raw_line.trim();
+                    if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                        continue;
+                    }
+                    if line.starts_with('[') {
+                        let name = line.trim_matches(|c| c == '[' || c == ']').trim();
+                        profiles.entry(name.to_string()).or_insert_with(Profile::default);
+                        current = Some(name.to_string());
+                        continue;
+                    }
+                    let name = current.clone().ok_or_else(|| {
+                        format!("{}:{}: entry outside of any `[section]`", path.display(), lineno + 1)
+                    })?;
+                    let mut parts = line.splitn(2, '=');
+                    let key = parts.next().unwrap_or("").trim();
+                    let value = parts.next().ok_or_else(|| {
+                        format!("{}:{}: expected `key = value`", path.display(), lineno + 1)
+                    })?.trim();
+                    profiles.get_mut(&name).expect("bug: section just inserted").entries
+                        .insert(key.to_string(), value.to_string());
+                }
+            }
+            Err(ref e) if e.kind() == ::std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).chain_err(|| format!("could not read {}", path.display())),
+        }
+        Ok(ProfileStore { path, profiles })
+    }
+
+    pub fn profile(&self, name: &str) -> Option<&Profile> {
+        self.profiles.get(name)
+    }
+
+    /// Add a new profile, or replace an existing one of the same name.
+    pub fn set_profile(&mut self, name: &str, profile: Profile) {
+        self.profiles.insert(name.to_string(), profile);
+    }
+
+    pub fn save(&self) -> ::Result<()> {
+        let mut out = String::new();
+        for (name, profile) in &self.profiles {
+            out.push_str(&format!("[{}]\n", name));
+            for key in &KEYS {
+                if let Some(value) = profile.entries.get(*key) {
+                    out.push_str(&format!("{} = {}\n", key, value));
+                }
+            }
+            // `--save-profile` rewrites every profile in the store from this
+            // in-memory map, so anything hand-written under a key we don't
+            // recognise (a typo, or one from a newer version of this tool)
+            // needs to round-trip too, or it's silently lost the next time
+            // *any* profile is saved.
+            for (key, value) in &profile.entries {
+                if !KEYS.contains(&key.as_str()) {
+                    out.push_str(&format!("{} = {}\n", key, value));
+                }
+            }
+            out.push('\n');
+        }
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)
+                .chain_err(|| format!("could not create {}", parent.display()))?;
+        }
+        let mut f = fs::File::create(&self.path)
+            .chain_err(|| format!("could not write {}", self.path.display()))?;
+        f.write_all(out.as_bytes())
+            .chain_err(|| format!("could not write {}", self.path.display()))
+    }
+}
+
+/// `~/.config/msi-rgb.conf` if `$HOME` is set, falling back to
+/// `/etc/msi-rgb.conf` otherwise.
+pub fn default_path() -> PathBuf {
+    if let Some(home) = env::var_os("HOME") {
+        let mut p = PathBuf::from(home);
+        p.push(".config");
+        p.push("msi-rgb.conf");
+        p
+    } else {
+        PathBuf::from("/etc/msi-rgb.conf")
+    }
+}
+
+/// Resolve a single-valued argument: an explicit CLI value wins, then the
+/// profile's value (if any), then whatever `clap` would've defaulted to.
+pub fn resolve_value<'a>(matches: &ArgMatches<'a>, profile: Option<&Profile>, arg: &str,
+                         key: &str) -> Option<String> {
+    if matches.occurrences_of(arg) > 0 {
+        return matches.value_of(arg).map(String::from);
+    }
+    if let Some(value) = profile.and_then(|p| p.get(key)) {
+        return Some(value.to_string());
+    }
+    matches.value_of(arg).map(String::from)
+}
+
+/// Resolve a boolean flag the same way `resolve_value` resolves a string.
+pub fn resolve_flag<'a>(matches: &ArgMatches<'a>, profile: Option<&Profile>, arg: &str,
+                        key: &str) -> bool {
+    if matches.occurrences_of(arg) > 0 {
+        return true;
+    }
+    if let Some(value) = profile.and_then(|p| p.get(key)) {
+        return value == "true";
+    }
+    matches.is_present(arg)
+}
+
+/// Resolve a multi-valued argument (`-i r -i g`, stored as `invert = r,g`).
+pub fn resolve_list<'a>(matches: &ArgMatches<'a>, profile: Option<&Profile>, arg: &str,
+                        key: &str) -> Vec<String> {
+    if matches.occurrences_of(arg) > 0 {
+        return matches.values_of(arg).map(|i| i.map(String::from).collect()).unwrap_or_default();
+    }
+    if let Some(value) = profile.and_then(|p| p.get(key)) {
+        return value.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect();
+    }
+    matches.values_of(arg).map(|i| i.map(String::from).collect()).unwrap_or_default()
+}
+
+/// Build a profile out of the arguments as `run()` would currently resolve
+/// them, for `--save-profile` to write out. `--color`/`--color-to` are
+/// resolved down to the same 32-bit `red`/`green`/`blue` words `run()` would
+/// otherwise pull straight from the CLI or an existing profile, so a saved
+/// profile is loadable with `--profile` regardless of which form produced it.
+pub fn profile_from_matches<'a>(matches: &ArgMatches<'a>, existing: Option<&Profile>) -> ::Result<Profile> {
+    let mut entries = BTreeMap::new();
+    if let Some(c1) = matches.value_of("COLOR") {
+        let c1 = ::color::Colour::parse(c1)?;
+        let c2 = match matches.value_of("COLOR_TO") {
+            Some(s) => Some(::color::Colour::parse(s)?),
+            None => None,
+        };
+        let (red, green, blue) = ::color::words(&c1, c2.as_ref());
+        entries.insert("red".to_string(), format!("{:x}", red));
+        entries.insert("green".to_string(), format!("{:x}", green));
+        entries.insert("blue".to_string(), format!("{:x}", blue));
+    } else {
+        for &(arg, key) in &[("RED", "red"), ("GREEN", "green"), ("BLUE", "blue")] {
+            if let Some(value) = resolve_value(matches, existing, arg, key) {
+                entries.insert(key.to_string(), value);
+            }
+        }
+    }
+    for &(arg, key) in &[("STEPDURATION", "duration"), ("BLINK", "blink")] {
+        if let Some(value) = resolve_value(matches, existing, arg, key) {
+            entries.insert(key.to_string(), value);
+        }
+    }
+    entries.insert("pulse".to_string(),
+                    resolve_flag(matches, existing, "PULSE", "pulse").to_string());
+    let invert = resolve_list(matches, existing, "INVHALF", "invert");
+    if !invert.is_empty() {
+        entries.insert("invert".to_string(), invert.join(","));
+    }
+    let fade_in = resolve_list(matches, existing, "FADE_IN", "fade-in");
+    if !fade_in.is_empty() {
+        entries.insert("fade-in".to_string(), fade_in.join(","));
+    }
+    Ok(Profile { entries })
+}
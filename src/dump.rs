@@ -0,0 +1,57 @@
+//! Backup/restore of the full chip RGB state.
+//!
+//! `--dump FILE` walks the same bank/register ranges `print_all` already
+//! knows about and writes a `(bank, register, value)` triple per line;
+//! `--restore FILE` reads such a file back and replays the triples through
+//! `write_byte_to_cell`, switching banks as needed along the way.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+
+use {inb, outb, write_byte_to_cell, check_chip_identity, DUMP_RANGES};
+use ResultExt;
+
+pub fn dump_to_file(f: &mut File, base_port: u16, path: &str) -> ::Result<()> {
+    let mut out = String::new();
+    for &(bank, s, e) in &DUMP_RANGES {
+        outb(f, base_port, 0x07)?;
+        outb(f, base_port + 1, bank)?;
+
+        for x in s..e {
+            let x = x as u8;
+            outb(f, base_port, x)?;
+            let d = inb(f, base_port + 1)?;
+            out.push_str(&format!("{:02x} {:02x} {:02x}\n", bank, x, d));
+        }
+    }
+    File::create(path).chain_err(|| format!("could not write {}", path))?
+        .write_all(out.as_bytes()).chain_err(|| format!("could not write {}", path))
+}
+
+pub fn restore_from_file(f: &mut File, base_port: u16, path: &str, ignore: bool) -> ::Result<()> {
+    check_chip_identity(f, base_port, ignore, false)?;
+
+    let file = File::open(path).chain_err(|| format!("could not read {}", path))?;
+    let mut current_bank: Option<u8> = None;
+    for (lineno, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.chain_err(|| format!("could not read {}", path))?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let mut parts = line.split_whitespace();
+        let malformed = || format!("{}:{}: expected `bank register value`, all in hex",
+                                    path, lineno + 1);
+        let bank = u8::from_str_radix(parts.next().ok_or_else(malformed)?, 16)?;
+        let reg = u8::from_str_radix(parts.next().ok_or_else(malformed)?, 16)?;
+        let value = u8::from_str_radix(parts.next().ok_or_else(malformed)?, 16)?;
+
+        if current_bank != Some(bank) {
+            write_byte_to_cell(f, base_port, 0x07, bank)?;
+            current_bank = Some(bank);
+        }
+        write_byte_to_cell(f, base_port, reg, value)?;
+    }
+    Ok(())
+}
@@ -63,11 +63,17 @@ extern crate error_chain;
 extern crate nix;
 
 mod platform;
+mod config;
+mod animate;
+mod dump;
+mod color;
 
 use std::io::Write;
 use std::fs;
+use std::time::Duration;
 use clap::{Arg, App, ArgMatches};
 use platform::{open_device, inb, outb};
+use config::Profile;
 
 error_chain! {
     foreign_links {
@@ -88,6 +94,10 @@ const REDCELL: u8 = 0xf0;
 const GREENCELL: u8 = 0xf4;
 const BLUECELL: u8 = 0xf8;
 
+/// Bank/start/end ranges `print_all` and `--dump` walk to cover the whole
+/// known RGB-relevant register space.
+const DUMP_RANGES: [(u8, u16, u16); 3] = [(RGB_BANK, 0xd0, 0x100), (0x09, 0x20, 0x40), (0x0b, 0x60, 0x70)];
+
 fn write_byte_to_cell(f: &mut fs::File, base_port: u16, cell: u8, data: u8) -> Result<()> {
     outb(f, base_port, cell)?;
     outb(f, base_port + 1, data)
@@ -100,37 +110,33 @@ fn write_colour(f: &mut fs::File, base_port: u16, cell_offset: u8, data: u32) ->
     write_byte_to_cell(f, base_port, cell_offset + 3, data as u8)
 }
 
-fn run<'a>(f: &mut fs::File, base_port: u16, matches: ArgMatches<'a>) -> Result<()> {
-    let disable = matches.is_present("DISABLE");
-    let pulse = matches.is_present("PULSE");
-    let ignore = matches.is_present("IGNORECHECK");
-    let flash = matches.value_of("BLINK").expect("bug: BLINK argument").parse::<u8>()?;
-    let red = u32::from_str_radix(matches.value_of("RED").expect("bug: RED argument"), 16)?;
-    let green = u32::from_str_radix(matches.value_of("GREEN").expect("bug: GREEN argument"), 16)?;
-    let blue = u32::from_str_radix(matches.value_of("BLUE").expect("bug: BLUE argument"), 16)?;
-    let step_duration = matches.value_of("STEPDURATION").expect("bug: STEPDURATION argument")
-                               .parse::<u16>()?;
-    let invs = matches.values_of("INVHALF").map(|i| i.collect()).unwrap_or(Vec::new());
-    let fade_in = matches.values_of("FADE_IN").map(|i| i.collect()).unwrap_or(Vec::new());
-
-    // Check if indeed a NCT6795D
-    if !ignore {
-        outb(f, base_port, REG_DEVID_MSB)?;
-        let msb = inb(f, base_port + 1)?;
-        outb(f, base_port, REG_DEVID_LSB)?;
-        let ident = (msb as u16) << 8 | inb(f, base_port + 1)? as u16;
-        if matches.is_present("VERBOSE")  {
-            println!("Chip identifier is: {:x}", ident);
-        }
-        if !VALID_MASKS.contains(&{ident & 0xFFF0}) {
-            let err: Result<()> = Err("`--ignore-check` flag, which would skip the check, \
-                                       is not specified (may be dangerous); \
-                                       also try `--base-port`".into());
-            return err.chain_err(|| format!("The sI/O chip identifies as {:x}, which does not \
-                                            seem to be NCT6795D", ident));
-        }
+/// Reads back the sI/O chip identifier and bails unless it looks like a
+/// NCT6795D/NCT6797, unless `ignore` is set.
+fn check_chip_identity(f: &mut fs::File, base_port: u16, ignore: bool, verbose: bool) -> Result<()> {
+    if ignore {
+        return Ok(());
     }
+    outb(f, base_port, REG_DEVID_MSB)?;
+    let msb = inb(f, base_port + 1)?;
+    outb(f, base_port, REG_DEVID_LSB)?;
+    let ident = (msb as u16) << 8 | inb(f, base_port + 1)? as u16;
+    if verbose {
+        println!("Chip identifier is: {:x}", ident);
+    }
+    if !VALID_MASKS.contains(&{ident & 0xFFF0}) {
+        let err: Result<()> = Err("`--ignore-check` flag, which would skip the check, \
+                                   is not specified (may be dangerous); \
+                                   also try `--base-port`".into());
+        return err.chain_err(|| format!("The sI/O chip identifies as {:x}, which does not \
+                                        seem to be NCT6795D", ident));
+    }
+    Ok(())
+}
 
+/// Selects the RGB register bank (`0x12`) and makes sure the header is under
+/// our control, so that `write_byte_to_cell`/`write_colour` calls land where
+/// expected. Idempotent — safe to call once up front and never again.
+fn enable_rgb_bank(f: &mut fs::File, base_port: u16) -> Result<()> {
     // Without this pulsing does not work
     outb(f, base_port, 0x07)?;
     outb(f, base_port + 1, 0x09)?;
@@ -150,6 +156,50 @@ fn run<'a>(f: &mut fs::File, base_port: u16, matches: ArgMatches<'a>) -> Result<
     if d & 0xe0 != 0xe0 {
         outb(f, base_port + 1, 0xe0 | (d & !0xe0))?;
     }
+    Ok(())
+}
+
+fn run<'a>(f: &mut fs::File, base_port: u16, matches: &ArgMatches<'a>,
+           profile: Option<&Profile>) -> Result<()> {
+    let disable = matches.is_present("DISABLE");
+    let pulse = config::resolve_flag(matches, profile, "PULSE", "pulse");
+    let ignore = matches.is_present("IGNORECHECK");
+    let flash = config::resolve_value(matches, profile, "BLINK", "blink")
+        .expect("bug: BLINK argument").parse::<u8>()?;
+    // `possible_values` only catches this when it comes straight off the CLI;
+    // a profile can hand us any string that parses as a u8, so re-check the
+    // same range here before it reaches the `(flash + 1) & 0b111` below.
+    if flash > 6 {
+        return Err(format!("blink must be between 0 and 6, got {}", flash).into());
+    }
+    let (red, green, blue) = if let Some(c1) = matches.value_of("COLOR") {
+        let c1 = color::Colour::parse(c1)?;
+        let c2 = match matches.value_of("COLOR_TO") {
+            Some(s) => Some(color::Colour::parse(s)?),
+            None => None,
+        };
+        color::words(&c1, c2.as_ref())
+    } else {
+        let red = u32::from_str_radix(&config::resolve_value(matches, profile, "RED", "red")
+            .ok_or("no value for RED (pass it directly, via --profile, or use --color)")?, 16)?;
+        let green = u32::from_str_radix(&config::resolve_value(matches, profile, "GREEN", "green")
+            .ok_or("no value for GREEN (pass it directly, via --profile, or use --color)")?, 16)?;
+        let blue = u32::from_str_radix(&config::resolve_value(matches, profile, "BLUE", "blue")
+            .ok_or("no value for BLUE (pass it directly, via --profile, or use --color)")?, 16)?;
+        (red, green, blue)
+    };
+    let step_duration = config::resolve_value(matches, profile, "STEPDURATION", "duration")
+        .expect("bug: STEPDURATION argument").parse::<u16>()?;
+    // Same story as BLINK above: the CLI's own `validator` never runs for a
+    // profile-sourced value.
+    if step_duration > 511 {
+        return Err(format!("duration must not exceed 511, got {}", step_duration).into());
+    }
+    let invs = config::resolve_list(matches, profile, "INVHALF", "invert");
+    let fade_in = config::resolve_list(matches, profile, "FADE_IN", "fade-in");
+
+    check_chip_identity(f, base_port, ignore, matches.is_present("VERBOSE"))?;
+    enable_rgb_bank(f, base_port)?;
 
     let e4_val = if disable { 1 } else { 0 } |
                  if pulse { 0b1000 } else { 0 } |
@@ -159,14 +209,15 @@ fn run<'a>(f: &mut fs::File, base_port: u16, matches: ArgMatches<'a>) -> Result<
     write_byte_to_cell(f, base_port, 0xfe, step_duration as u8)?;
 
 
+    let has = |list: &[String], c: &str| list.iter().any(|s| s == c);
     let ff_fade_in_val = 0b11100000u8 & // no fading in at all.
-        if fade_in.contains(&"b") { !0b10000000 } else { !0 } &
-        if fade_in.contains(&"g") { !0b01000000 } else { !0 } &
-        if fade_in.contains(&"r") { !0b00100000 } else { !0 };
+        if has(&fade_in, "b") { !0b10000000 } else { !0 } &
+        if has(&fade_in, "g") { !0b01000000 } else { !0 } &
+        if has(&fade_in, "r") { !0b00100000 } else { !0 };
     let ff_invert_val = 0u8 |
-        if invs.contains(&"b") { 0b00010000 } else { 0 } |
-        if invs.contains(&"g") { 0b00001000 } else { 0 } |
-        if invs.contains(&"r") { 0b00000100 } else { 0 } ;
+        if has(&invs, "b") { 0b00010000 } else { 0 } |
+        if has(&invs, "g") { 0b00001000 } else { 0 } |
+        if has(&invs, "r") { 0b00000100 } else { 0 } ;
     let ff_val = (step_duration >> 8) as u8 & 0b1 | // The extra bit for step duration
                  0b10 | // if 0 disable lights on rgb header only, not on board
                  ff_invert_val | ff_fade_in_val;
@@ -180,7 +231,7 @@ fn run<'a>(f: &mut fs::File, base_port: u16, matches: ArgMatches<'a>) -> Result<
 }
 
 fn print_all(f: &mut fs::File, base_port: u16) -> Result<()> {
-    for &(bank, s, e) in &[(RGB_BANK, 0xd0, 0x100u16), (0x09, 0x20, 0x40), (0x0b, 0x60, 0x70)] {
+    for &(bank, s, e) in &DUMP_RANGES {
         println!("Bank {:02x} ({:02x}...{:02x}):", bank, s, e);
         outb(f, base_port, 0x07)?;
         outb(f, base_port + 1, bank)?;
@@ -204,6 +255,19 @@ fn run_wrap<'a>(matches: ArgMatches<'a>) -> Result<()> {
     let base_port = u16::from_str_radix(matches.value_of("BASEPORT")
                                                .expect("bug: BASEPORT argument"), 16)?;
 
+    let mut store = config::ProfileStore::load(config::default_path())?;
+    let profile = match matches.value_of("PROFILE") {
+        Some(name) => Some(store.profile(name).cloned()
+            .ok_or_else(|| format!("no such profile: {} (check your config file)", name))?),
+        None => None,
+    };
+
+    if let Some(name) = matches.value_of("SAVE_PROFILE") {
+        let new_profile = config::profile_from_matches(&matches, profile.as_ref())?;
+        store.set_profile(name, new_profile);
+        store.save()?;
+    }
+
     let mut f = open_device()?;
     // Enable the advanced mode.
     outb(&mut f, base_port, 0x87).chain_err(|| "could not enable advanced mode")?;
@@ -222,7 +286,19 @@ fn run_wrap<'a>(matches: ArgMatches<'a>) -> Result<()> {
         print_all(&mut f, base_port)?;
     }
 
-    let r = run(&mut f, base_port, matches);
+    let r = if let Some(path) = matches.value_of("DUMP") {
+        dump::dump_to_file(&mut f, base_port, path)
+    } else if let Some(path) = matches.value_of("RESTORE") {
+        dump::restore_from_file(&mut f, base_port, path, matches.is_present("IGNORECHECK"))
+    } else if let Some(mode) = matches.value_of("ANIMATE") {
+        let mode = animate::Mode::from_arg(mode, matches.value_of("THERMAL_SENSOR"))?;
+        let tick_ms = matches.value_of("ANIMATE_INTERVAL").expect("bug: ANIMATE_INTERVAL argument")
+                              .parse::<u64>()?;
+        animate::run_daemon(&mut f, base_port, mode, Duration::from_millis(tick_ms),
+                            matches.is_present("IGNORECHECK"))
+    } else {
+        run(&mut f, base_port, &matches, profile.as_ref())
+    };
     // Disable the advanced mode.
     outb(&mut f, base_port, 0xAA).chain_err(|| "could not disable advanced mode")?;
     r.chain_err(|| "could not set the colour")
@@ -233,12 +309,47 @@ fn main() {
         .version(option_env!("CARGO_PKG_VERSION").unwrap_or("0.0.0"))
         .about(option_env!("CARGO_PKG_DESCRIPTION").unwrap_or(""))
         .setting(clap::AppSettings::ArgRequiredElseHelp)
-        .arg(Arg::with_name("RED").required(true)
+        .arg(Arg::with_name("RED")
+             .required_unless_one(&["PROFILE", "ANIMATE", "DUMP", "RESTORE", "COLOR"])
              .help("values of red colour (32 bit hex number, up to FFFFFFFF)"))
-        .arg(Arg::with_name("GREEN").required(true)
+        .arg(Arg::with_name("GREEN")
+             .required_unless_one(&["PROFILE", "ANIMATE", "DUMP", "RESTORE", "COLOR"])
              .help("values of green colour (32 bit hex number, up to FFFFFFFF)"))
-        .arg(Arg::with_name("BLUE").required(true)
+        .arg(Arg::with_name("BLUE")
+             .required_unless_one(&["PROFILE", "ANIMATE", "DUMP", "RESTORE", "COLOR"])
              .help("values of blue colour (32 bit hex number, up to FFFFFFFF)"))
+        .arg(Arg::with_name("COLOR").long("color").alias("colour").takes_value(true)
+             .value_name("#RRGGBB")
+             .help("fill all 8 frames of each channel from a standard 24 bit colour instead of \
+                   RED/GREEN/BLUE, gamma-corrected down to the chip's 4-bit levels"))
+        .arg(Arg::with_name("COLOR_TO").long("color-to").alias("colour-to").takes_value(true)
+             .value_name("#RRGGBB").requires("COLOR")
+             .help("a second colour to fade to across the 8 hardware frames (requires --color)"))
+        .arg(Arg::with_name("DUMP").long("dump").takes_value(true).value_name("FILE")
+             .help("write the current contents of the known RGB-relevant register ranges to \
+                   FILE instead of writing a colour"))
+        .arg(Arg::with_name("RESTORE").long("restore").takes_value(true).value_name("FILE")
+             .conflicts_with("DUMP")
+             .help("replay a register dump written by --dump back onto the chip, instead of \
+                   writing a colour"))
+        .arg(Arg::with_name("PROFILE").long("profile").takes_value(true)
+             .help("load RED/GREEN/BLUE and friends from a named profile in the config file \
+                   (~/.config/msi-rgb.conf)"))
+        .arg(Arg::with_name("SAVE_PROFILE").long("save-profile").takes_value(true)
+             .value_name("NAME")
+             .help("save the (possibly just-loaded) settings as a named profile in the config \
+                   file, creating or replacing it"))
+        .arg(Arg::with_name("ANIMATE").long("animate").takes_value(true)
+             .possible_values(&["rainbow", "thermal"])
+             .requires_if("thermal", "THERMAL_SENSOR")
+             .help("keep the device open and recompute the frames every tick instead of writing \
+                   them once; see also --animate-interval and --thermal-sensor"))
+        .arg(Arg::with_name("ANIMATE_INTERVAL").long("animate-interval").takes_value(true)
+             .default_value("50")
+             .help("milliseconds between animation ticks when --animate is given"))
+        .arg(Arg::with_name("THERMAL_SENSOR").long("thermal-sensor").takes_value(true)
+             .help("sysfs file to read a millidegree temperature from, e.g. \
+                   /sys/class/thermal/thermal_zone0/temp (required by `--animate thermal`)"))
         .arg(Arg::with_name("INVHALF").long("invert").short("i").multiple(true)
              .takes_value(true).possible_values(&["r","g","b"])
              .help("invert the specified channel(s)"))